@@ -10,92 +10,345 @@ use std::cmp::Eq;
 use std::collections::hash_map::HashMap;
 use std::hash::Hash;
 use std::io::{Read, Write, Seek, SeekFrom, ErrorKind as IoErrorKind};
+use std::fs::{self, File, OpenOptions};
 use std::marker::PhantomData;
+use std::path::Path;
 
+/// The store operation that was in flight when an error occurred.
 #[derive(Debug)]
-pub enum LogKvError {
+pub enum Op {
+    /// Rebuilding the index in `create`.
+    Recovery,
+    /// Looking a value up in `get`.
+    Get,
+    /// Appending a record in `put`.
+    Put,
+    /// No operation context was attached (e.g. a plain IO error).
+    Unknown,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Op::Recovery => "Recovery",
+            Op::Get => "Get",
+            Op::Put => "Put",
+            Op::Unknown => "Unknown",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The underlying cause of a [`LogKvError`].
+#[derive(Debug)]
+pub enum LogKvErrorKind {
     Io(io::Error),
     EncodingError(EncodingError),
     DecodingError(DecodingError),
+    CorruptRecord,
+    Codec(Box<error::Error>),
+}
+
+impl LogKvErrorKind {
+    fn label(&self) -> &'static str {
+        match *self {
+            LogKvErrorKind::Io(_) => "IO error",
+            LogKvErrorKind::EncodingError(_) => "encode error",
+            LogKvErrorKind::DecodingError(_) => "decode error",
+            LogKvErrorKind::CorruptRecord => "corrupt record",
+            LogKvErrorKind::Codec(_) => "codec error",
+        }
+    }
+
+    fn source(&self) -> Option<&error::Error> {
+        match *self {
+            LogKvErrorKind::Io(ref err) => Some(err),
+            LogKvErrorKind::EncodingError(ref err) => Some(err),
+            LogKvErrorKind::DecodingError(ref err) => Some(err),
+            LogKvErrorKind::CorruptRecord => None,
+            LogKvErrorKind::Codec(ref err) => Some(&**err),
+        }
+    }
+}
+
+/// An error from the store, carrying the byte offset and operation that
+/// triggered it so callers can pinpoint which record in the log is bad.
+#[derive(Debug)]
+pub struct LogKvError {
+    pub kind: LogKvErrorKind,
+    pub offset: Option<u64>,
+    pub op: Op,
+}
+
+impl LogKvError {
+    /// Builds a `CorruptRecord` error anchored at `offset` during recovery.
+    fn corrupt(offset: u64) -> LogKvError {
+        LogKvError {
+            kind: LogKvErrorKind::CorruptRecord,
+            offset: Some(offset),
+            op: Op::Recovery,
+        }
+    }
+
+    /// Attaches positional context to an error, recording the `op` it happened
+    /// in and the byte `offset` it happened at.
+    fn at(mut self, op: Op, offset: u64) -> LogKvError {
+        self.op = op;
+        self.offset = Some(offset);
+        self
+    }
 }
 
 impl fmt::Display for LogKvError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            LogKvError::Io(ref err) => write!(f, "IO error: {}", err),
-            LogKvError::EncodingError(ref err) => write!(f, "Encoding error: {}", err),
-            LogKvError::DecodingError(ref err) => write!(f, "Decoding error: {}", err),
+        write!(f, "{}", self.kind.label())?;
+        if let Some(offset) = self.offset {
+            write!(f, " at offset {}", offset)?;
+        }
+        match self.op {
+            Op::Unknown => {}
+            ref op => write!(f, " during {}", op)?,
         }
+        if let Some(cause) = self.kind.source() {
+            write!(f, ": {}", cause)?;
+        }
+        Ok(())
     }
 }
 
 impl error::Error for LogKvError {
     fn description(&self) -> &str {
-        match *self {
-            LogKvError::Io(ref err) => err.description(),
-            LogKvError::EncodingError(ref err) => err.description(),
-            LogKvError::DecodingError(ref err) => err.description(),
-        }
+        self.kind.label()
     }
 
     fn cause(&self) -> Option<&error::Error> {
-        match *self {
-            LogKvError::Io(ref err) => Some(err),
-            LogKvError::EncodingError(ref err) => Some(err),
-            LogKvError::DecodingError(ref err) => Some(err),
-        }
+        self.kind.source()
     }
 }
 
 impl From<io::Error> for LogKvError {
     fn from(err: io::Error) -> LogKvError {
-        LogKvError::Io(err)
+        LogKvError { kind: LogKvErrorKind::Io(err), offset: None, op: Op::Unknown }
     }
 }
 
 impl From<EncodingError> for LogKvError {
     fn from(err: EncodingError) -> LogKvError {
-        LogKvError::EncodingError(err)
+        LogKvError { kind: LogKvErrorKind::EncodingError(err), offset: None, op: Op::Unknown }
     }
 }
 
 impl From<DecodingError> for LogKvError {
     fn from(err: DecodingError) -> LogKvError {
-        LogKvError::DecodingError(err)
+        LogKvError { kind: LogKvErrorKind::DecodingError(err), offset: None, op: Op::Unknown }
+    }
+}
+
+/// Record tag for a live key-value entry.
+const RECORD_LIVE: u8 = 0;
+/// Record tag for a tombstone marking a deleted key.
+const RECORD_TOMBSTONE: u8 = 1;
+
+/// Size in bytes of the framing fields (length prefix and trailing CRC).
+const LEN_BYTES: u64 = 4;
+const TAG_BYTES: u64 = 1;
+
+/// Computes the IEEE CRC-32 of `bytes` (polynomial `0xEDB88320`).
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFF_FFFF;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn read_u32(buf: &[u8]) -> u32 {
+    (buf[0] as u32) | ((buf[1] as u32) << 8) | ((buf[2] as u32) << 16) | ((buf[3] as u32) << 24)
+}
+
+fn write_u32(buf: &mut [u8], value: u32) {
+    buf[0] = value as u8;
+    buf[1] = (value >> 8) as u8;
+    buf[2] = (value >> 16) as u8;
+    buf[3] = (value >> 24) as u8;
+}
+
+/// Reads up to `buf.len()` bytes, returning how many were actually read. A
+/// return value short of the buffer length signals a torn tail (or clean EOF
+/// when it is zero).
+fn fill<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == IoErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+/// Writes `payload` framed as `[u32 total_len][payload][u32 crc32]`.
+fn write_frame<W: Write>(writer: &mut W, payload: &[u8]) -> io::Result<()> {
+    let mut len_buf = [0u8; 4];
+    write_u32(&mut len_buf, payload.len() as u32);
+    writer.write_all(&len_buf)?;
+    writer.write_all(payload)?;
+    let mut crc_buf = [0u8; 4];
+    write_u32(&mut crc_buf, crc32(payload));
+    writer.write_all(&crc_buf)?;
+    Ok(())
+}
+
+/// Pluggable serialization backend for a [`LogKv`].
+///
+/// The store itself only cares about turning keys and values into bytes and
+/// back; the concrete wire format is left to the codec so callers can swap in
+/// an alternative (fixed-endian, self-describing, ...) without forking the
+/// store. [`BincodeCodec`] is the default and preserves the historic format.
+pub trait Codec {
+    /// Encodes `value` into `writer`.
+    fn encode_into<W, S>(&self, value: &S, writer: &mut W) -> Result<(), LogKvError>
+        where W: Write,
+              S: Encodable;
+
+    /// Decodes a value of type `D` from `reader`.
+    fn decode_from<R, D>(&self, reader: &mut R) -> Result<D, LogKvError>
+        where R: Read,
+              D: Decodable;
+}
+
+/// The default [`Codec`], backed by `bincode` with an infinite size limit.
+#[derive(Clone, Copy, Default)]
+pub struct BincodeCodec;
+
+impl Codec for BincodeCodec {
+    fn encode_into<W, S>(&self, value: &S, writer: &mut W) -> Result<(), LogKvError>
+        where W: Write,
+              S: Encodable
+    {
+        encode_into(value, writer, SizeLimit::Infinite).map_err(LogKvError::from)
+    }
+
+    fn decode_from<R, D>(&self, reader: &mut R) -> Result<D, LogKvError>
+        where R: Read,
+              D: Decodable
+    {
+        decode_from(reader, SizeLimit::Infinite).map_err(LogKvError::from)
     }
 }
 
-pub struct LogKv<K, V, T> {
+pub struct LogKv<K, V, T, C = BincodeCodec> {
     cursor: T,
     index: HashMap<K, u64>,
+    codec: C,
     _phantom: PhantomData<V>,
 }
 
-impl<K, V, T> LogKv<K, V, T>
+impl<K, V, T> LogKv<K, V, T, BincodeCodec>
     where K: Encodable + Decodable + Eq + Hash,
           V: Encodable + Decodable,
           T: Read + Write + Seek
 {
-    pub fn create(cursor: T) -> Result<LogKv<K, V, T>, LogKvError> {
+    /// Opens a store over `cursor` using the default [`BincodeCodec`].
+    pub fn create(cursor: T) -> Result<LogKv<K, V, T, BincodeCodec>, LogKvError> {
+        LogKv::create_with_codec(cursor, BincodeCodec)
+    }
+}
+
+impl<K, V, T, C> LogKv<K, V, T, C>
+    where K: Encodable + Decodable + Eq + Hash,
+          V: Encodable + Decodable,
+          T: Read + Write + Seek,
+          C: Codec
+{
+    /// Opens a store over `cursor`, routing all serialization through `codec`.
+    pub fn create_with_codec(cursor: T, codec: C) -> Result<LogKv<K, V, T, C>, LogKvError> {
         let mut logkv = LogKv {
             cursor: cursor,
             index: HashMap::new(),
+            codec: codec,
             _phantom: PhantomData,
         };
 
         logkv.cursor.seek(SeekFrom::Start(0))?;
         loop {
-            let key = match decode_from::<T, K>(&mut logkv.cursor, SizeLimit::Infinite) {
-                Ok(key) => key,
-                Err(DecodingError::IoError(ref e)) if e.kind() == IoErrorKind::UnexpectedEof => {
-                    break;
-                }
-                Err(e) => return Err(LogKvError::from(e)),
+            let record_start = logkv.cursor.seek(SeekFrom::Current(0))?;
+
+            let mut len_buf = [0u8; 4];
+            let got = fill(&mut logkv.cursor, &mut len_buf)?;
+            if got == 0 {
+                break;
+            }
+            if got < len_buf.len() {
+                // Torn length prefix — a crash truncated the tail. Rewind so
+                // the next put overwrites the garbage.
+                logkv.cursor.seek(SeekFrom::Start(record_start))?;
+                break;
+            }
+
+            let total_len = read_u32(&len_buf) as usize;
+
+            // Every record we write carries at least a tag byte, so a zero
+            // payload length can only be corruption — surface it rather than
+            // indexing an empty payload below.
+            if total_len == 0 {
+                return Err(LogKvError::corrupt(record_start));
+            }
+
+            // Bound the payload length against the bytes actually left in the
+            // log (payload plus the trailing CRC) so a garbage length prefix
+            // can't trigger a huge allocation before the torn-tail check runs.
+            let payload_pos = logkv.cursor.seek(SeekFrom::Current(0))?;
+            let end = logkv.cursor.seek(SeekFrom::End(0))?;
+            logkv.cursor.seek(SeekFrom::Start(payload_pos))?;
+            if (total_len as u64) + LEN_BYTES > end - payload_pos {
+                logkv.cursor.seek(SeekFrom::Start(record_start))?;
+                break;
+            }
+
+            let mut payload = vec![0u8; total_len];
+            let got = fill(&mut logkv.cursor, &mut payload)?;
+            let mut crc_buf = [0u8; 4];
+            let got_crc = if got == total_len {
+                fill(&mut logkv.cursor, &mut crc_buf)?
+            } else {
+                0
             };
+            if got < total_len || got_crc < crc_buf.len() {
+                // Torn payload or CRC — same treatment as a torn prefix.
+                logkv.cursor.seek(SeekFrom::Start(record_start))?;
+                break;
+            }
 
-            let position = logkv.cursor.seek(SeekFrom::Current(0))?;
+            if crc32(&payload) != read_u32(&crc_buf) {
+                // A checksum failure means the tail past this record is no
+                // longer trustworthy. Stop recovery at the last fully-valid
+                // record and rewind so subsequent puts overwrite the garbage,
+                // keeping reopen deterministic.
+                logkv.cursor.seek(SeekFrom::Start(record_start))?;
+                break;
+            }
+
+            let tag = payload[0];
+            let mut body = &payload[1..];
+            let key: K = logkv.codec
+                .decode_from(&mut body)
+                .map_err(|e| e.at(Op::Recovery, record_start))?;
+
+            if tag == RECORD_TOMBSTONE {
+                logkv.index.remove(&key);
+                continue;
+            }
+
+            let key_len = (payload.len() - 1 - body.len()) as u64;
+            let position = record_start + LEN_BYTES + TAG_BYTES + key_len;
             logkv.index.insert(key, position);
-            decode_from::<T, V>(&mut logkv.cursor, SizeLimit::Infinite)?;
         }
 
         Ok(logkv)
@@ -118,34 +371,313 @@ impl<K, V, T> LogKv<K, V, T>
     /// assert_eq!(retrieved, value);
     /// ```
     pub fn put(&mut self, key: K, value: V) -> Result<(), LogKvError> {
-        encode_into(&key, &mut self.cursor, SizeLimit::Infinite)?;
-        let position = self.cursor.seek(SeekFrom::Current(0))?;
+        let record_start = self.cursor.seek(SeekFrom::Current(0))?;
+        let mut payload = Vec::new();
+        payload.push(RECORD_LIVE);
+        self.codec.encode_into(&key, &mut payload)?;
+        let key_len = (payload.len() as u64) - TAG_BYTES;
+        let position = record_start + LEN_BYTES + TAG_BYTES + key_len;
+        self.codec
+            .encode_into(&value, &mut payload)
+            .map_err(|e| e.at(Op::Put, position))?;
+        write_frame(&mut self.cursor, &payload)?;
         self.index.insert(key, position);
-        encode_into(&value, &mut self.cursor, SizeLimit::Infinite)?;
         Ok(())
     }
 
+    /// Removes a key from the store.
+    ///
+    /// The backing log is append-only, so rather than rewriting history this
+    /// appends a tombstone record (a record tag followed by the key with no
+    /// value) and drops the key from the in-memory index. On the next
+    /// `create` the tombstone removes the key from the rebuilt index, so the
+    /// deletion survives a reopen.
+    pub fn delete(&mut self, key: K) -> Result<(), LogKvError> {
+        let mut payload = Vec::new();
+        payload.push(RECORD_TOMBSTONE);
+        self.codec.encode_into(&key, &mut payload)?;
+        write_frame(&mut self.cursor, &payload)?;
+        self.index.remove(&key);
+        Ok(())
+    }
+
+    /// Rewrites the live contents of the store into `target`, returning a new
+    /// `LogKv` backed by it.
+    ///
+    /// The index already points at the newest value for every live key, so we
+    /// walk it, decode each value from the current log and re-append a single
+    /// fresh record per key into `target`. Overwritten and tombstoned records
+    /// are never visited, so the space they occupied is physically reclaimed.
+    pub fn compact<U>(&mut self, mut target: U) -> Result<LogKv<K, V, U, C>, LogKvError>
+        where U: Read + Write + Seek,
+              K: Clone,
+              C: Clone
+    {
+        target.seek(SeekFrom::Start(0))?;
+        let resume = self.cursor.seek(SeekFrom::Current(0))?;
+        let mut new_index = HashMap::new();
+        let entries: Vec<(K, u64)> = self.index
+            .iter()
+            .map(|(key, &offset)| (key.clone(), offset))
+            .collect();
+
+        for (key, offset) in entries {
+            self.cursor.seek(SeekFrom::Start(offset))?;
+            let value: V = self.codec.decode_from(&mut self.cursor)?;
+            let record_start = target.seek(SeekFrom::Current(0))?;
+            let mut payload = Vec::new();
+            payload.push(RECORD_LIVE);
+            self.codec.encode_into(&key, &mut payload)?;
+            let key_len = (payload.len() as u64) - TAG_BYTES;
+            self.codec.encode_into(&value, &mut payload)?;
+            write_frame(&mut target, &payload)?;
+            let position = record_start + LEN_BYTES + TAG_BYTES + key_len;
+            new_index.insert(key, position);
+        }
+        self.cursor.seek(SeekFrom::Start(resume))?;
+
+        Ok(LogKv {
+            cursor: target,
+            index: new_index,
+            codec: self.codec.clone(),
+            _phantom: PhantomData,
+        })
+    }
+
     pub fn get(&mut self, key: K) -> Result<Option<V>, LogKvError> {
         return match self.index.get(&key) {
             Some(position) => {
-                self.cursor.seek(SeekFrom::Start(*position))?;
-                let value = decode_from(&mut self.cursor, SizeLimit::Infinite)?;
+                let position = *position;
+                let resume = self.cursor.seek(SeekFrom::Current(0))?;
+                self.cursor.seek(SeekFrom::Start(position))?;
+                let value = self.codec
+                    .decode_from(&mut self.cursor)
+                    .map_err(|e| e.at(Op::Get, position))?;
+                self.cursor.seek(SeekFrom::Start(resume))?;
                 Ok(Some(value))
             }
             None => Ok(None),
         };
     }
+
+    /// Returns an iterator over the keys currently held in the store.
+    ///
+    /// Keys are yielded in arbitrary order and without touching the backing
+    /// cursor, so callers can enumerate the store without knowing the keys up
+    /// front.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.index.keys()
+    }
+
+    /// Returns an iterator over the live `(key, value)` pairs in the store.
+    ///
+    /// The index already records the newest offset per live key, so the
+    /// iterator borrows the cursor and decodes each value lazily on `next`
+    /// rather than materializing the whole store at once.
+    pub fn iter(&mut self) -> Result<Iter<K, V, T, C>, LogKvError>
+        where K: Clone
+    {
+        let entries: Vec<(K, u64)> = self.index
+            .iter()
+            .map(|(key, &offset)| (key.clone(), offset))
+            .collect();
+
+        Ok(Iter {
+            cursor: &mut self.cursor,
+            codec: &self.codec,
+            entries: entries.into_iter(),
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Starts a [`WriteBatch`] that buffers mutations and applies them as a
+    /// single atomic `commit`.
+    pub fn batch(&mut self) -> WriteBatch<K, V, T, C> {
+        WriteBatch {
+            db: self,
+            buffer: Vec::new(),
+            ops: Vec::new(),
+        }
+    }
+}
+
+/// A pending mutation buffered inside a [`WriteBatch`].
+enum BatchOp<K> {
+    /// Insert `key` at the value offset the buffered record will occupy.
+    Insert(K, u64),
+    /// Remove `key` from the index.
+    Remove(K),
+}
+
+/// A group of buffered mutations that commit together, returned by
+/// [`LogKv::batch`].
+///
+/// Records are encoded into an in-memory buffer as they are queued; `commit`
+/// performs a single `write_all`/`flush` to the cursor and only then applies
+/// the corresponding index updates. If the durable write fails the index is
+/// left untouched, so a group of changes is all-or-nothing from the caller's
+/// point of view.
+pub struct WriteBatch<'a, K: 'a, V: 'a, T: 'a, C: 'a> {
+    db: &'a mut LogKv<K, V, T, C>,
+    buffer: Vec<u8>,
+    ops: Vec<BatchOp<K>>,
+}
+
+impl<'a, K, V, T, C> WriteBatch<'a, K, V, T, C>
+    where K: Encodable + Decodable + Eq + Hash,
+          V: Encodable + Decodable,
+          T: Read + Write + Seek,
+          C: Codec
+{
+    /// Queues a key-value pair to be written on `commit`.
+    pub fn put(&mut self, key: K, value: V) -> Result<(), LogKvError> {
+        let mut payload = Vec::new();
+        payload.push(RECORD_LIVE);
+        self.db.codec.encode_into(&key, &mut payload)?;
+        let key_len = (payload.len() as u64) - TAG_BYTES;
+        self.db.codec.encode_into(&value, &mut payload)?;
+        let record_start = self.buffer.len() as u64;
+        write_frame(&mut self.buffer, &payload)?;
+        let offset = record_start + LEN_BYTES + TAG_BYTES + key_len;
+        self.ops.push(BatchOp::Insert(key, offset));
+        Ok(())
+    }
+
+    /// Queues a tombstone removing `key` on `commit`.
+    pub fn delete(&mut self, key: K) -> Result<(), LogKvError> {
+        let mut payload = Vec::new();
+        payload.push(RECORD_TOMBSTONE);
+        self.db.codec.encode_into(&key, &mut payload)?;
+        write_frame(&mut self.buffer, &payload)?;
+        self.ops.push(BatchOp::Remove(key));
+        Ok(())
+    }
+
+    /// Flushes the buffered records in one write and applies the index updates.
+    ///
+    /// The buffered offsets are relative to the start of the batch, so they are
+    /// rebased onto the cursor's position before being recorded. Nothing
+    /// touches the index until the durable write and flush both succeed.
+    pub fn commit(self) -> Result<(), LogKvError> {
+        let base = self.db.cursor.seek(SeekFrom::Current(0))?;
+        self.db.cursor.write_all(&self.buffer)?;
+        self.db.cursor.flush()?;
+
+        for op in self.ops {
+            match op {
+                BatchOp::Insert(key, offset) => {
+                    self.db.index.insert(key, base + offset);
+                }
+                BatchOp::Remove(key) => {
+                    self.db.index.remove(&key);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over the live `(key, value)` pairs of a [`LogKv`], returned by
+/// [`LogKv::iter`]. Values are decoded on demand from the borrowed cursor.
+pub struct Iter<'a, K: 'a, V, T: 'a, C: 'a> {
+    cursor: &'a mut T,
+    codec: &'a C,
+    entries: ::std::vec::IntoIter<(K, u64)>,
+    _phantom: PhantomData<V>,
+}
+
+impl<'a, K, V, T, C> Iterator for Iter<'a, K, V, T, C>
+    where V: Decodable,
+          T: Read + Seek,
+          C: Codec
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        let (key, offset) = self.entries.next()?;
+        self.cursor.seek(SeekFrom::Start(offset)).ok()?;
+        let value = self.codec.decode_from(&mut *self.cursor).ok()?;
+        Some((key, value))
+    }
+}
+
+impl<K, V, C> LogKv<K, V, File, C>
+    where K: Encodable + Decodable + Eq + Hash + Clone,
+          V: Encodable + Decodable,
+          C: Codec + Clone
+{
+    /// Compacts a file-backed store in place.
+    ///
+    /// Writes the live records to a sibling temporary file, atomically renames
+    /// it over `path`, then reopens the freshly compacted log as the new
+    /// backing cursor.
+    pub fn compact_in_place<P: AsRef<Path>>(&mut self, path: P) -> Result<(), LogKvError> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("compact.tmp");
+        {
+            let temp_file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&tmp)?;
+            self.compact(temp_file)?;
+        }
+
+        fs::rename(&tmp, path)?;
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let reopened: LogKv<K, V, File, C> = LogKv::create_with_codec(file, self.codec.clone())?;
+        self.cursor = reopened.cursor;
+        self.index = reopened.index;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::LogKv;
+    use super::{BincodeCodec, LogKv, LogKvErrorKind};
     use uuid::Uuid;
     use std::string::String;
     use std::fs::remove_file;
     use std::fs;
     use std::io::Cursor;
 
+    #[test]
+    fn compact_in_place_keeps_latest_and_drops_deleted() {
+        let path = "compact_in_place_keeps_latest_and_drops_deleted";
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path)
+            .unwrap();
+
+        let kept = Uuid::new_v4();
+        let gone = Uuid::new_v4();
+        {
+            let mut db = LogKv::create(file).unwrap();
+            db.put(kept, String::from("old")).unwrap();
+            db.put(kept, String::from("new")).unwrap();
+            db.put(gone, String::from("bye")).unwrap();
+            db.delete(gone).unwrap();
+            db.compact_in_place(path).unwrap();
+            assert_eq!(db.get(kept).unwrap().unwrap(), "new");
+            assert!(db.get(gone).unwrap().is_none());
+        }
+
+        let reopened_file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .unwrap();
+        let mut reopened: LogKv<Uuid, String, _> = LogKv::create(reopened_file).unwrap();
+        let survived = reopened.get(kept).unwrap().expect("live key lost after compaction");
+        let removed = reopened.get(gone).unwrap();
+        remove_file(path).unwrap();
+        assert_eq!(survived, "new");
+        assert!(removed.is_none());
+    }
+
     #[test]
     fn put_twice_then_get_returns_expected() {
         let file = fs::OpenOptions::new()
@@ -165,6 +697,170 @@ mod tests {
         assert_eq!(retrieved, value);
     }
 
+    #[test]
+    fn delete_then_get_returns_none_across_reopen() {
+        let mut cursor = Cursor::new(Vec::new());
+        let key = Uuid::new_v4();
+        {
+            let mut db = LogKv::create(&mut cursor).unwrap();
+            db.put(key, String::from("doomed")).unwrap();
+            db.delete(key).unwrap();
+            assert!(db.get(key).unwrap().is_none());
+        }
+
+        cursor.set_position(0);
+        let mut reopened: LogKv<Uuid, String, _> = LogKv::create(&mut cursor).unwrap();
+        assert!(reopened.get(key).unwrap().is_none());
+    }
+
+    #[test]
+    fn compact_keeps_latest_and_drops_deleted() {
+        let mut cursor = Cursor::new(Vec::new());
+        let kept = Uuid::new_v4();
+        let gone = Uuid::new_v4();
+        let mut db = LogKv::create(&mut cursor).unwrap();
+        db.put(kept, String::from("old")).unwrap();
+        db.put(kept, String::from("new")).unwrap();
+        db.put(gone, String::from("bye")).unwrap();
+        db.delete(gone).unwrap();
+
+        let target = Cursor::new(Vec::new());
+        let mut compacted = db.compact(target).unwrap();
+        assert_eq!(compacted.get(kept).unwrap().unwrap(), "new");
+        assert!(compacted.get(gone).unwrap().is_none());
+    }
+
+    #[test]
+    fn torn_tail_is_truncated_on_reopen() {
+        let mut cursor = Cursor::new(Vec::new());
+        let good = Uuid::new_v4();
+        {
+            let mut db = LogKv::create(&mut cursor).unwrap();
+            db.put(good, String::from("intact")).unwrap();
+        }
+
+        // Simulate a crash mid-write: a length prefix promising more payload
+        // than actually made it to disk.
+        let mut bytes = cursor.into_inner();
+        bytes.extend_from_slice(&[10, 0, 0, 0, 0x00]);
+
+        let mut cursor = Cursor::new(bytes);
+        let mut db: LogKv<Uuid, String, _> = LogKv::create(&mut cursor).unwrap();
+        assert_eq!(db.get(good).unwrap().unwrap(), "intact");
+
+        // The garbage tail was rewound, so a fresh put overwrites it cleanly.
+        let more = Uuid::new_v4();
+        db.put(more, String::from("after")).unwrap();
+        assert_eq!(db.get(more).unwrap().unwrap(), "after");
+    }
+
+    #[test]
+    fn crc_mismatch_truncates_at_last_good_record() {
+        let mut cursor = Cursor::new(Vec::new());
+        let good = Uuid::new_v4();
+        let rotted = Uuid::new_v4();
+        {
+            let mut db = LogKv::create(&mut cursor).unwrap();
+            db.put(good, String::from("intact")).unwrap();
+            db.put(rotted, String::from("rotten")).unwrap();
+        }
+
+        // Corrupt the second record's payload so only the first survives.
+        let mut bytes = cursor.into_inner();
+        let rot = bytes.len() - 6;
+        bytes[rot] ^= 0xFF;
+
+        let mut cursor = Cursor::new(bytes);
+        let mut db: LogKv<Uuid, String, _> = LogKv::create(&mut cursor).unwrap();
+        assert_eq!(db.get(good).unwrap().unwrap(), "intact");
+        assert!(db.get(rotted).unwrap().is_none());
+
+        // Recovery rewound to the last good record, so a fresh put lands
+        // cleanly after it.
+        let more = Uuid::new_v4();
+        db.put(more, String::from("after")).unwrap();
+        assert_eq!(db.get(more).unwrap().unwrap(), "after");
+    }
+
+    #[test]
+    fn zero_length_record_surfaces_corrupt_record() {
+        // A length prefix of 0 followed by a zeroed CRC used to pass the
+        // checksum and then panic indexing an empty payload.
+        let mut cursor = Cursor::new(vec![0u8; 8]);
+        let result: Result<LogKv<Uuid, String, _>, _> = LogKv::create(&mut cursor);
+        match result {
+            Err(ref e) => {
+                match e.kind {
+                    LogKvErrorKind::CorruptRecord => {}
+                    ref other => panic!("expected CorruptRecord, got {:?}", other),
+                }
+                assert_eq!(e.offset, Some(0));
+            }
+            Ok(_) => panic!("expected CorruptRecord error"),
+        }
+    }
+
+    #[test]
+    fn iter_yields_latest_live_pairs() {
+        let mut cursor = Cursor::new(Vec::new());
+        let a = Uuid::new_v4();
+        let b = Uuid::new_v4();
+        let gone = Uuid::new_v4();
+        let mut db = LogKv::create(&mut cursor).unwrap();
+        db.put(a, String::from("old")).unwrap();
+        db.put(a, String::from("new")).unwrap();
+        db.put(b, String::from("bee")).unwrap();
+        db.put(gone, String::from("bye")).unwrap();
+        db.delete(gone).unwrap();
+
+        let keys: Vec<Uuid> = db.keys().cloned().collect();
+        assert_eq!(keys.len(), 2);
+        assert!(keys.contains(&a) && keys.contains(&b));
+
+        let mut pairs: Vec<(Uuid, String)> = db.iter().unwrap().collect();
+        pairs.sort_by_key(|&(k, _)| k);
+        let mut expected = vec![(a, String::from("new")), (b, String::from("bee"))];
+        expected.sort_by_key(|&(k, _)| k);
+        assert_eq!(pairs, expected);
+    }
+
+    #[test]
+    fn create_with_codec_round_trips() {
+        let mut cursor = Cursor::new(Vec::new());
+        let key = Uuid::new_v4();
+        let mut db = LogKv::create_with_codec(&mut cursor, BincodeCodec).unwrap();
+        db.put(key, String::from("via codec")).unwrap();
+        assert_eq!(db.get(key).unwrap().unwrap(), "via codec");
+    }
+
+    #[test]
+    fn batch_commits_atomically_and_survives_reopen() {
+        let mut cursor = Cursor::new(Vec::new());
+        let a = Uuid::new_v4();
+        let b = Uuid::new_v4();
+        let gone = Uuid::new_v4();
+        {
+            let mut db = LogKv::create(&mut cursor).unwrap();
+            db.put(gone, String::from("seed")).unwrap();
+
+            let mut batch = db.batch();
+            batch.put(a, String::from("one")).unwrap();
+            batch.put(b, String::from("two")).unwrap();
+            batch.delete(gone).unwrap();
+            batch.commit().unwrap();
+
+            assert_eq!(db.get(a).unwrap().unwrap(), "one");
+            assert_eq!(db.get(b).unwrap().unwrap(), "two");
+            assert!(db.get(gone).unwrap().is_none());
+        }
+
+        cursor.set_position(0);
+        let mut reopened: LogKv<Uuid, String, _> = LogKv::create(&mut cursor).unwrap();
+        assert_eq!(reopened.get(a).unwrap().unwrap(), "one");
+        assert_eq!(reopened.get(b).unwrap().unwrap(), "two");
+        assert!(reopened.get(gone).unwrap().is_none());
+    }
+
     #[test]
     fn get_returns_not_found() {
         let file = fs::OpenOptions::new()